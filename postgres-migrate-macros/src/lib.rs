@@ -0,0 +1,95 @@
+//! The procedural macro backing `postgres::migrate`.
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate sha2;
+extern crate syn;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use sha2::{Digest, Sha256};
+use syn::{parse_macro_input, LitStr};
+
+/// Expands to a `&'static [postgres::migrate::Migration]` built from every
+/// `NNN_description.sql` file in `dir`, which is resolved relative to the
+/// crate root.
+///
+/// Each file becomes one `Migration` with its SQL embedded via
+/// `include_str!` -- so editing a migration file triggers a rebuild -- and
+/// a SHA-256 checksum of its raw bytes, computed once here so `Migrator`
+/// never has to re-read migration files that have already been applied.
+#[proc_macro]
+pub fn migrate(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr).value();
+    let root = Path::new(&env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set"))
+        .join(&dir);
+
+    let paths: Vec<_> = fs::read_dir(&root)
+        .unwrap_or_else(|e| panic!("failed to read migration directory {:?}: {}", root, e))
+        .map(|entry| entry.expect("failed to read migration directory entry").path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "sql"))
+        .collect();
+
+    // The documented invariant is ascending *version* order (see
+    // `migrate::Migrator`), not filesystem order -- sorting paths
+    // lexicographically would apply `10_j.sql` before `2_b.sql` unless
+    // every file name happened to be zero-padded to the same width.
+    let mut migrations: Vec<_> = paths
+        .iter()
+        .map(|path| {
+            let file_name = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_else(|| panic!("migration file name {:?} is not valid UTF-8", path));
+
+            let idx = file_name.find('_').unwrap_or_else(|| {
+                panic!(
+                    "migration file name {:?} is not of the form NNN_description.sql",
+                    file_name
+                )
+            });
+            let (version, description) = file_name.split_at(idx);
+            let description = description[1..].to_owned();
+            let version: i64 = version.parse().unwrap_or_else(|_| {
+                panic!(
+                    "migration file name {:?} does not start with a version number",
+                    file_name
+                )
+            });
+
+            (version, description, path)
+        })
+        .collect();
+    migrations.sort_by_key(|&(version, _, _)| version);
+
+    for window in migrations.windows(2) {
+        let (a, b) = (&window[0], &window[1]);
+        if a.0 == b.0 {
+            panic!("migration version {} is used by both {:?} and {:?}", a.0, a.2, b.2);
+        }
+    }
+
+    let migrations = migrations.iter().map(|(version, description, path)| {
+        let bytes = fs::read(path).unwrap_or_else(|e| panic!("failed to read {:?}: {}", path, e));
+        let checksum = Sha256::digest(&bytes);
+        let checksum = checksum.as_slice();
+        let path = path.to_str().unwrap_or_else(|| panic!("path {:?} is not valid UTF-8", path));
+
+        quote! {
+            ::postgres::migrate::Migration {
+                version: #version,
+                description: #description,
+                sql: include_str!(#path),
+                checksum: &[#(#checksum),*],
+            }
+        }
+    });
+
+    TokenStream::from(quote! {
+        &[#(#migrations),*] as &[::postgres::migrate::Migration]
+    })
+}
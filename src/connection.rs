@@ -0,0 +1,243 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use error::{DbError, Error, Result};
+use message::{self, BackendMessage, FrontendMessage};
+
+/// The shared state backing a `Connection` (and the statements and
+/// transactions derived from it).
+///
+/// Any I/O error encountered while writing a frontend message or
+/// interpreting a backend message leaves the stream at an unknown point in
+/// the protocol, so `desynchronized` is set and every subsequent call is
+/// rejected via `check_desync!` until either the connection is dropped or
+/// `resync` restores it to a known state.
+pub struct InnerConnection<S> {
+    stream: S,
+    desynchronized: bool,
+}
+
+impl<S: Read + Write> InnerConnection<S> {
+    #[doc(hidden)]
+    pub fn new(stream: S) -> InnerConnection<S> {
+        InnerConnection {
+            stream,
+            desynchronized: false,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn is_desynchronized(&self) -> bool {
+        self.desynchronized
+    }
+
+    /// Attempts to bring a desynchronized connection back to a usable
+    /// state.
+    ///
+    /// Many causes of desynchronization (a partially consumed result set,
+    /// an aborted `COPY`, an unexpected message following an error) leave
+    /// the wire protocol itself intact -- the server is simply not done
+    /// talking yet. This sends a `Sync` message and then discards backend
+    /// messages until a `ReadyForQuery` is seen, at which point the
+    /// connection is known to be idle and synchronized again.
+    ///
+    /// If a genuine I/O error occurs while resynchronizing (for example
+    /// the socket has been closed), that error is returned and the
+    /// connection is left marked as desynchronized, since in that case
+    /// there is no recoverable protocol state to return to.
+    ///
+    /// Does nothing and returns `Ok(())` if the connection is not
+    /// currently desynchronized.
+    pub fn resync(&mut self) -> Result<()> {
+        if !self.desynchronized {
+            return Ok(());
+        }
+
+        try_desync!(self, message::write_message(FrontendMessage::Sync, &mut self.stream));
+
+        loop {
+            match try_desync!(self, message::read_message(&mut self.stream)) {
+                BackendMessage::ReadyForQuery => break,
+                // Errors and notices encountered while draining are
+                // expected -- they're exactly the backlog resync exists to
+                // clear -- so they're discarded along with everything else.
+                BackendMessage::ErrorResponse { .. }
+                | BackendMessage::NoticeResponse
+                | BackendMessage::DataRow { .. }
+                | BackendMessage::Unknown => continue,
+            }
+        }
+
+        self.desynchronized = false;
+        Ok(())
+    }
+
+    /// Runs `query`, which may contain multiple `;`-separated statements,
+    /// via the simple query protocol, discarding any rows it returns.
+    fn batch_execute(&mut self, query: &str) -> Result<()> {
+        self.simple_query(query)?;
+        Ok(())
+    }
+
+    /// Runs `query` via the simple query protocol, collecting every row it
+    /// returns in text format.
+    ///
+    /// A server error does not end the simple query protocol exchange --
+    /// Postgres always follows an `ErrorResponse` with a `ReadyForQuery` of
+    /// its own, so the error is stashed and the loop keeps draining until
+    /// that `ReadyForQuery` is seen. Returning as soon as the error arrives
+    /// would leave it unread and desynchronize the connection by exactly
+    /// one frame, without ever setting `desynchronized` to say so.
+    fn simple_query(&mut self, query: &str) -> Result<Vec<Vec<Option<Vec<u8>>>>> {
+        check_desync!(self);
+
+        try_desync!(self, message::write_message(FrontendMessage::Query(query), &mut self.stream));
+
+        let mut rows = vec![];
+        let mut error = None;
+        loop {
+            match try_desync!(self, message::read_message(&mut self.stream)) {
+                BackendMessage::ReadyForQuery => {
+                    return match error {
+                        Some(err) => Err(Error::DbError(Box::new(err))),
+                        None => Ok(rows),
+                    };
+                }
+                BackendMessage::ErrorResponse { fields } => {
+                    let mut fields = message::ErrorFields::new(&fields);
+                    error = Some(try_desync!(self, DbError::new(&mut fields)));
+                }
+                BackendMessage::DataRow { values } => rows.push(values),
+                BackendMessage::NoticeResponse | BackendMessage::Unknown => continue,
+            }
+        }
+    }
+}
+
+/// A connection to a Postgres server.
+pub struct Connection {
+    inner: InnerConnection<TcpStream>,
+}
+
+impl Connection {
+    /// Opens a new connection to a Postgres server listening at `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Connection> {
+        let stream = TcpStream::connect(addr).map_err(Error::IoError)?;
+        Ok(Connection {
+            inner: InnerConnection::new(stream),
+        })
+    }
+
+    /// Returns `true` if an earlier I/O error has left the connection's
+    /// protocol state unknown.
+    pub fn is_desynchronized(&self) -> bool {
+        self.inner.is_desynchronized()
+    }
+
+    /// Attempts to bring a desynchronized connection back to a usable
+    /// state. See `InnerConnection::resync`.
+    pub fn resync(&mut self) -> Result<()> {
+        self.inner.resync()
+    }
+
+    /// Runs a trivial empty query against the server and waits for the
+    /// response, as a lightweight check that the connection is actually
+    /// alive and idle.
+    pub fn ping(&mut self) -> Result<()> {
+        self.inner.batch_execute(";")
+    }
+
+    /// Executes `query`, which may contain multiple `;`-separated
+    /// statements and must not take any parameters, discarding any rows it
+    /// returns.
+    pub fn batch_execute(&mut self, query: &str) -> Result<()> {
+        self.inner.batch_execute(query)
+    }
+
+    /// Executes `query` via the simple query protocol and returns its rows
+    /// in text format, one `Vec` of nullable column values per row.
+    pub fn query(&mut self, query: &str) -> Result<Vec<Vec<Option<Vec<u8>>>>> {
+        self.inner.simple_query(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use super::*;
+
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+    }
+
+    impl MockStream {
+        fn new(input: Vec<u8>) -> MockStream {
+            MockStream { input: Cursor::new(input) }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn error_response() -> Vec<u8> {
+        let mut fields = vec![];
+        for &(tag, value) in &[(b'S', "ERROR"), (b'C', "23505"), (b'M', "duplicate key")] {
+            fields.push(tag);
+            fields.extend_from_slice(value.as_bytes());
+            fields.push(0);
+        }
+        fields.push(0);
+
+        let mut message = vec![b'E'];
+        message.extend_from_slice(&((fields.len() + 4) as i32).to_be_bytes());
+        message.extend_from_slice(&fields);
+        message
+    }
+
+    fn ready_for_query() -> Vec<u8> {
+        vec![b'Z', 0, 0, 0, 5, b'I']
+    }
+
+    #[test]
+    fn simple_query_error_drains_its_own_ready_for_query() {
+        let mut input = error_response();
+        input.extend_from_slice(&ready_for_query());
+        // A second, independent ReadyForQuery standing in for the next
+        // query's response. A bug that stops consuming at the
+        // ErrorResponse would desync by exactly one frame and read this
+        // one instead of failing or setting `desynchronized`.
+        input.extend_from_slice(&ready_for_query());
+
+        let mut conn = InnerConnection::new(MockStream::new(input));
+
+        assert!(conn.simple_query("select 1 / 0").is_err());
+        assert!(!conn.is_desynchronized());
+        assert!(conn.simple_query("select 1").is_ok());
+    }
+
+    #[test]
+    fn resync_drains_to_ready_for_query() {
+        let mut input = error_response();
+        input.extend_from_slice(&ready_for_query());
+
+        let mut conn = InnerConnection::new(MockStream::new(input));
+        conn.desynchronized = true;
+
+        assert!(conn.resync().is_ok());
+        assert!(!conn.is_desynchronized());
+    }
+}
@@ -0,0 +1,301 @@
+//! Errors.
+use std::error;
+use std::fmt;
+use std::io;
+
+use message::ErrorFields;
+
+/// The Unicode scalar-value offset of a server-reported error or notice
+/// within the query that triggered it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorPosition {
+    /// A position in the original query, or an internal position that the
+    /// server reported without the text of its internal query.
+    Normal(u32),
+    /// A position in an internally-generated query, for example one run by
+    /// a PL/pgSQL function. Contains the text of that query as reported by
+    /// the server.
+    Internal {
+        /// The character (Unicode scalar value) position, as reported by
+        /// the server.
+        position: u32,
+        /// The text of the internally-generated query.
+        query: String,
+    },
+}
+
+/// A Postgres error or notice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DbError {
+    /// The field contents are ERROR, FATAL, or PANIC (in an error message),
+    /// or WARNING, NOTICE, DEBUG, INFO, or LOG (in a notice message).
+    pub severity: String,
+    /// The SQLSTATE code for the error.
+    pub code: String,
+    /// The primary human-readable error message.
+    pub message: String,
+    /// An optional secondary error message carrying more detail about the
+    /// problem.
+    pub detail: Option<String>,
+    /// An optional suggestion on what to do about the problem.
+    pub hint: Option<String>,
+    /// An optional error cursor position into either the original query
+    /// string or an internally generated query.
+    pub position: Option<ErrorPosition>,
+    /// An indication of the context in which the error occurred.
+    pub where_: Option<String>,
+    /// If the error was associated with a specific table, the name of the
+    /// schema containing that table.
+    pub schema: Option<String>,
+    /// If the error was associated with a specific table, the name of the
+    /// table.
+    pub table: Option<String>,
+    /// If the error was associated with a specific table column, the name
+    /// of the column.
+    pub column: Option<String>,
+    /// If the error was associated with a specific data type, the name of
+    /// the data type.
+    pub datatype: Option<String>,
+    /// If the error was associated with a specific constraint, the name of
+    /// the constraint.
+    pub constraint: Option<String>,
+    /// The source-code file the error was reported from, if the server
+    /// provides it.
+    pub file: Option<String>,
+    /// The source-code line the error was reported from, if the server
+    /// provides it.
+    pub line: Option<u32>,
+    /// The name of the source-code routine the error was reported from, if
+    /// the server provides it.
+    pub routine: Option<String>,
+}
+
+impl DbError {
+    #[doc(hidden)]
+    pub fn new(fields: &mut ErrorFields) -> io::Result<DbError> {
+        let mut severity = None;
+        let mut code = None;
+        let mut message = None;
+        let mut detail = None;
+        let mut hint = None;
+        let mut normal_position = None;
+        let mut internal_position = None;
+        let mut internal_query = None;
+        let mut where_ = None;
+        let mut schema = None;
+        let mut table = None;
+        let mut column = None;
+        let mut datatype = None;
+        let mut constraint = None;
+        let mut file = None;
+        let mut line = None;
+        let mut routine = None;
+
+        for field in fields {
+            let (type_, value) = field?;
+            match type_ {
+                b'S' => severity = Some(value),
+                b'C' => code = Some(value),
+                b'M' => message = Some(value),
+                b'D' => detail = Some(value),
+                b'H' => hint = Some(value),
+                b'P' => normal_position = value.parse().ok(),
+                b'p' => internal_position = value.parse().ok(),
+                b'q' => internal_query = Some(value),
+                b'W' => where_ = Some(value),
+                b's' => schema = Some(value),
+                b't' => table = Some(value),
+                b'c' => column = Some(value),
+                b'd' => datatype = Some(value),
+                b'n' => constraint = Some(value),
+                b'F' => file = Some(value),
+                b'L' => line = value.parse().ok(),
+                b'R' => routine = Some(value),
+                _ => {}
+            }
+        }
+
+        let position = match (normal_position, internal_position, internal_query) {
+            (Some(position), _, _) => Some(ErrorPosition::Normal(position)),
+            (_, Some(position), Some(query)) => Some(ErrorPosition::Internal {
+                position,
+                query,
+            }),
+            // The server reported an internal position with no internal
+            // query text to go with it -- keep the position rather than
+            // discarding it. It is stored as `ErrorPosition::Normal`, so
+            // `DbError::position` resolves it against the query passed in,
+            // not against the internal query the server counted it in.
+            (_, Some(position), None) => Some(ErrorPosition::Normal(position)),
+            _ => None,
+        };
+
+        Ok(DbError {
+            severity: severity.ok_or_else(::bad_response)?,
+            code: code.ok_or_else(::bad_response)?,
+            message: message.ok_or_else(::bad_response)?,
+            detail,
+            hint,
+            position,
+            where_,
+            schema,
+            table,
+            column,
+            datatype,
+            constraint,
+            file,
+            line,
+            routine,
+        })
+    }
+
+    /// Converts the server-reported error position into a 1-based
+    /// `(line, column)` pair within `query`.
+    ///
+    /// `query` should be the text of the query that produced this error. If
+    /// the position refers to an internally-generated query (for example
+    /// one run from within a PL/pgSQL function), the position is instead
+    /// resolved against that internal query, which the server reports
+    /// alongside the position and is independent of `query`. An internal
+    /// position reported without its query text is resolved against
+    /// `query`.
+    ///
+    /// Returns `None` if the server did not report a position.
+    pub fn position(&self, query: &str) -> Option<(u32, u32)> {
+        match self.position {
+            Some(ErrorPosition::Normal(position)) => Some(line_col(query, position)),
+            Some(ErrorPosition::Internal { position, ref query }) => {
+                Some(line_col(query, position))
+            }
+            None => None,
+        }
+    }
+}
+
+// PostgreSQL reports cursor positions as a 1-based offset into the query
+// text counted in characters (Unicode scalar values), not bytes.
+fn line_col(query: &str, position: u32) -> (u32, u32) {
+    if position == 0 {
+        return (1, 1);
+    }
+
+    let mut line = 1;
+    let mut column = 1;
+
+    for c in query.chars().take(position as usize - 1) {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    (line, column)
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// An error encountered when communicating with a Postgres server.
+#[derive(Debug)]
+pub enum Error {
+    /// An error reported by the Postgres server.
+    DbError(Box<DbError>),
+    /// An I/O error.
+    IoError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::DbError(ref e) => write!(fmt, "{}", e),
+            Error::IoError(ref e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::DbError(_) => "database error",
+            Error::IoError(_) => "IO error",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Error::DbError(ref e) => Some(e),
+            Error::IoError(ref e) => Some(e),
+        }
+    }
+}
+
+impl error::Error for DbError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::IoError(e)
+    }
+}
+
+/// A specialized result type for Postgres operations.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_zero_position_does_not_underflow() {
+        assert_eq!(line_col("select 1", 0), (1, 1));
+    }
+
+    #[test]
+    fn line_col_first_character() {
+        assert_eq!(line_col("select 1", 1), (1, 1));
+    }
+
+    #[test]
+    fn line_col_counts_characters_not_bytes() {
+        assert_eq!(line_col("select 'é' +", 12), (1, 12));
+    }
+
+    #[test]
+    fn line_col_resets_column_on_newline() {
+        assert_eq!(line_col("select 1\nfrom bogus", 15), (2, 6));
+    }
+
+    fn fields_buf(fields: &[(u8, &str)]) -> Vec<u8> {
+        let mut buf = vec![];
+        for &(type_, value) in fields {
+            buf.push(type_);
+            buf.extend_from_slice(value.as_bytes());
+            buf.push(0);
+        }
+        buf.push(0);
+        buf
+    }
+
+    #[test]
+    fn new_keeps_bare_internal_position_without_an_internal_query() {
+        let buf = fields_buf(&[
+            (b'S', "ERROR"),
+            (b'C', "42601"),
+            (b'M', "syntax error"),
+            (b'p', "7"),
+        ]);
+        let mut fields = ErrorFields::new(&buf);
+
+        let err = DbError::new(&mut fields).unwrap();
+
+        assert_eq!(err.position, Some(ErrorPosition::Normal(7)));
+    }
+}
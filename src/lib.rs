@@ -0,0 +1,35 @@
+//! A native PostgreSQL driver.
+#![warn(missing_docs)]
+
+extern crate byteorder;
+#[cfg(feature = "pool")]
+extern crate r2d2;
+#[cfg(feature = "migrate")]
+extern crate postgres_migrate_macros;
+
+#[macro_use]
+mod macros;
+
+mod connection;
+pub mod error;
+#[cfg(feature = "migrate")]
+pub mod migrate;
+mod message;
+#[cfg(feature = "pool")]
+pub mod pool;
+
+pub use connection::Connection;
+#[cfg(feature = "migrate")]
+pub use postgres_migrate_macros::migrate;
+
+use std::io;
+
+fn bad_response() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "bad response from server")
+}
+
+fn desynchronized() -> io::Error {
+    io::Error::other(
+        "connection is desynchronized due to an earlier IO error",
+    )
+}
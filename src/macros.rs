@@ -17,11 +17,3 @@ macro_rules! check_desync {
         }
     })
 }
-
-macro_rules! bad_response {
-    ($s:expr) => ({
-        debug!("Bad response at {}:{}", file!(), line!());
-        $s.desynchronized = true;
-        return Err(::error::Error::IoError(::bad_response()));
-    })
-}
@@ -0,0 +1,126 @@
+use std::io::{self, Read, Write};
+use std::str;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// A message sent from the client to the server.
+pub enum FrontendMessage<'a> {
+    /// Requests that the server process all outstanding messages and
+    /// return to a known, idle state with a `ReadyForQuery`.
+    Sync,
+    /// Runs `query` using the simple query protocol.
+    Query(&'a str),
+}
+
+/// A message sent from the server to the client.
+pub enum BackendMessage {
+    /// The server is ready for a new query.
+    ReadyForQuery,
+    /// The server reported an error.
+    ErrorResponse { fields: Vec<u8> },
+    /// The server reported a notice.
+    NoticeResponse,
+    /// A row of query results, in text format.
+    DataRow { values: Vec<Option<Vec<u8>>> },
+    /// A message this crate does not otherwise interpret.
+    Unknown,
+}
+
+/// Writes a frontend message to `w`.
+pub fn write_message<W: Write>(message: FrontendMessage, w: &mut W) -> io::Result<()> {
+    match message {
+        FrontendMessage::Sync => {
+            w.write_u8(b'S')?;
+            w.write_i32::<BigEndian>(4)?;
+        }
+        FrontendMessage::Query(query) => {
+            w.write_u8(b'Q')?;
+            w.write_i32::<BigEndian>(4 + query.len() as i32 + 1)?;
+            w.write_all(query.as_bytes())?;
+            w.write_u8(0)?;
+        }
+    }
+    w.flush()
+}
+
+/// Reads a single backend message from `r`.
+pub fn read_message<R: Read>(r: &mut R) -> io::Result<BackendMessage> {
+    let tag = r.read_u8()?;
+    let len = r.read_i32::<BigEndian>()?;
+    let mut data = vec![0; len as usize - 4];
+    r.read_exact(&mut data)?;
+
+    Ok(match tag {
+        b'Z' => BackendMessage::ReadyForQuery,
+        b'E' => BackendMessage::ErrorResponse { fields: data },
+        b'N' => BackendMessage::NoticeResponse,
+        b'D' => BackendMessage::DataRow { values: parse_data_row(&data)? },
+        _ => BackendMessage::Unknown,
+    })
+}
+
+fn parse_data_row(mut buf: &[u8]) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let num_values = (&mut buf).read_i16::<BigEndian>()?;
+    let mut values = Vec::with_capacity(num_values as usize);
+
+    for _ in 0..num_values {
+        let len = (&mut buf).read_i32::<BigEndian>()?;
+        if len < 0 {
+            values.push(None);
+            continue;
+        }
+
+        let len = len as usize;
+        if buf.len() < len {
+            return Err(::bad_response());
+        }
+        let (value, rest) = buf.split_at(len);
+        values.push(Some(value.to_vec()));
+        buf = rest;
+    }
+
+    Ok(values)
+}
+
+/// An iterator over the (type, value) fields of a PostgreSQL `ErrorResponse`
+/// or `NoticeResponse` message body.
+///
+/// The field list is terminated by a zero byte in place of a type code.
+pub struct ErrorFields<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ErrorFields<'a> {
+    pub fn new(buf: &'a [u8]) -> ErrorFields<'a> {
+        ErrorFields { buf }
+    }
+}
+
+impl<'a> Iterator for ErrorFields<'a> {
+    type Item = io::Result<(u8, String)>;
+
+    fn next(&mut self) -> Option<io::Result<(u8, String)>> {
+        let type_ = match self.buf.first() {
+            Some(&b) => b,
+            None => return Some(Err(::bad_response())),
+        };
+        self.buf = &self.buf[1..];
+
+        if type_ == 0 {
+            return None;
+        }
+
+        let end = match self.buf.iter().position(|&b| b == 0) {
+            Some(end) => end,
+            None => return Some(Err(::bad_response())),
+        };
+
+        let value = match str::from_utf8(&self.buf[..end]) {
+            Ok(value) => value.to_owned(),
+            Err(_) => return Some(Err(::bad_response())),
+        };
+        self.buf = &self.buf[end + 1..];
+
+        Some(Ok((type_, value)))
+    }
+}
@@ -0,0 +1,249 @@
+//! Embedded, checksum-verified schema migrations.
+//!
+//! Pair this module with the `migrate!` macro from the
+//! `postgres-migrate-macros` crate to compile a directory of
+//! `NNN_description.sql` files directly into the binary:
+//!
+//! ```ignore
+//! static MIGRATIONS: &[Migration] = migrate!("migrations");
+//!
+//! fn run(conn: &mut Connection) -> postgres::error::Result<()> {
+//!     Migrator::new(MIGRATIONS).run(conn)
+//! }
+//! ```
+use error::{Error, Result};
+use Connection;
+
+/// A single versioned schema migration.
+///
+/// Normally produced by the `migrate!` macro rather than constructed by
+/// hand.
+pub struct Migration {
+    /// The migration's version, taken from the `NNN` prefix of its file
+    /// name. Migrations are applied in ascending version order.
+    pub version: i64,
+    /// The human-readable part of the file name, used for bookkeeping and
+    /// error messages.
+    pub description: &'static str,
+    /// The migration's SQL, embedded with `include_str!` so that editing
+    /// the file triggers a rebuild.
+    pub sql: &'static str,
+    /// A SHA-256 checksum of the migration file's bytes, computed at
+    /// build time.
+    pub checksum: &'static [u8],
+}
+
+/// Applies a set of `Migration`s to a `Connection`, tracking which have
+/// already run in a `_migrations` bookkeeping table.
+pub struct Migrator<'a> {
+    migrations: &'a [Migration],
+}
+
+impl<'a> Migrator<'a> {
+    /// Creates a new `Migrator` over `migrations`, which must be sorted by
+    /// version (the slice produced by `migrate!` already is).
+    pub fn new(migrations: &'a [Migration]) -> Migrator<'a> {
+        Migrator { migrations }
+    }
+
+    /// Creates the bookkeeping table if it doesn't already exist, then
+    /// applies every migration whose version isn't yet recorded, each
+    /// inside its own transaction.
+    ///
+    /// Before applying anything, every already-applied migration's stored
+    /// checksum is compared against the checksum embedded in `self`. A
+    /// mismatch means the migration file was edited after being applied --
+    /// which would otherwise let the schema silently drift out of step
+    /// with the migration history -- so `run` refuses to continue and
+    /// returns an error naming the offending version and description.
+    pub fn run(&self, conn: &mut Connection) -> Result<()> {
+        conn.batch_execute(
+            "CREATE TABLE IF NOT EXISTS _migrations (
+                 version     BIGINT PRIMARY KEY,
+                 description TEXT NOT NULL,
+                 checksum    BYTEA NOT NULL,
+                 applied_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+             )",
+        )?;
+
+        let applied = self.applied_migrations(conn)?;
+        self.verify_checksums(&applied)?;
+
+        for migration in self.migrations {
+            if applied.iter().any(|a| a.version == migration.version) {
+                continue;
+            }
+            self.apply(conn, migration)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks every already-applied migration's stored checksum against
+    /// the checksum embedded in `self`, as a pass of its own, before
+    /// anything is applied.
+    ///
+    /// Checking checksums in version order interleaved with applying new
+    /// migrations would let a tampered old migration go unnoticed until
+    /// the loop reached it, by which point later, lower-numbered
+    /// migrations may already have been applied and committed.
+    fn verify_checksums(&self, applied: &[AppliedMigration]) -> Result<()> {
+        for applied in applied {
+            let migration = match self
+                .migrations
+                .iter()
+                .find(|migration| migration.version == applied.version)
+            {
+                Some(migration) => migration,
+                None => continue,
+            };
+
+            if migration.checksum != &applied.checksum[..] {
+                return Err(checksum_mismatch(migration));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn applied_migrations(&self, conn: &mut Connection) -> Result<Vec<AppliedMigration>> {
+        let rows = conn.query("SELECT version, checksum FROM _migrations")?;
+
+        let mut applied = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut row = row.into_iter();
+            let version = text_column(&mut row)?;
+            let checksum = text_column(&mut row)?;
+
+            applied.push(AppliedMigration {
+                version: version.parse().map_err(|_| ::bad_response())?,
+                checksum: decode_hex_bytea(&checksum)?,
+            });
+        }
+        Ok(applied)
+    }
+
+    /// Runs `migration.sql` and records it in `_migrations`, both inside a
+    /// single transaction, rolling the transaction back if either step
+    /// fails.
+    ///
+    /// This depends on `Connection::batch_execute` fully draining the
+    /// server's response -- including the `ReadyForQuery` that follows an
+    /// `ErrorResponse` -- before returning, so that the `ROLLBACK` sent
+    /// here is read as a fresh exchange rather than against a stream still
+    /// holding a leftover frame from the failed statement.
+    fn apply(&self, conn: &mut Connection, migration: &Migration) -> Result<()> {
+        conn.batch_execute("BEGIN")?;
+
+        let result = conn.batch_execute(migration.sql).and_then(|()| {
+            conn.batch_execute(&format!(
+                "INSERT INTO _migrations (version, description, checksum) \
+                 VALUES ({}, {}, {})",
+                migration.version,
+                escape_literal(migration.description),
+                hex_bytea(migration.checksum),
+            ))
+        });
+
+        match result {
+            Ok(()) => conn.batch_execute("COMMIT"),
+            Err(e) => {
+                // Best-effort: if the rollback itself fails, the original
+                // error is still the more useful one to report.
+                let _ = conn.batch_execute("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+}
+
+struct AppliedMigration {
+    version: i64,
+    checksum: Vec<u8>,
+}
+
+fn checksum_mismatch(migration: &Migration) -> Error {
+    Error::IoError(::std::io::Error::new(
+        ::std::io::ErrorKind::InvalidData,
+        format!(
+            "migration {} ({}) has already been applied, but its checksum no longer \
+             matches the migration file on disk",
+            migration.version, migration.description
+        ),
+    ))
+}
+
+fn text_column(row: &mut ::std::vec::IntoIter<Option<Vec<u8>>>) -> Result<String> {
+    let value = row.next().ok_or_else(::bad_response)?;
+    let value = value.ok_or_else(::bad_response)?;
+    String::from_utf8(value).map_err(|_| Error::IoError(::bad_response()))
+}
+
+fn escape_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn hex_bytea(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(3 + bytes.len() * 2);
+    s.push_str("'\\x");
+    for b in bytes {
+        s.push_str(&format!("{:02x}", b));
+    }
+    s.push_str("'::bytea");
+    s
+}
+
+fn decode_hex_bytea(s: &str) -> Result<Vec<u8>> {
+    let s = s.strip_prefix("\\x").ok_or_else(::bad_response)?;
+    if s.len() % 2 != 0 {
+        return Err(Error::IoError(::bad_response()));
+    }
+
+    let mut bytes = Vec::with_capacity(s.len() / 2);
+    for i in (0..s.len()).step_by(2) {
+        let byte = u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| ::bad_response())?;
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(version: i64, checksum: &'static [u8]) -> Migration {
+        Migration {
+            version,
+            description: "test",
+            sql: "",
+            checksum,
+        }
+    }
+
+    #[test]
+    fn decode_hex_bytea_parses_hex_output() {
+        assert_eq!(decode_hex_bytea("\\xdeadbeef").unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn verify_checksums_passes_when_matching() {
+        let migrations = [migration(1, &[1, 2, 3])];
+        let migrator = Migrator::new(&migrations);
+        let applied = [AppliedMigration { version: 1, checksum: vec![1, 2, 3] }];
+
+        assert!(migrator.verify_checksums(&applied).is_ok());
+    }
+
+    #[test]
+    fn verify_checksums_catches_a_tampered_old_migration_before_newer_ones_apply() {
+        // Version 1 was applied with a checksum that no longer matches its
+        // migration file; version 2 is newer and not yet applied. Even
+        // though version 2 comes later in iteration order, the mismatch at
+        // version 1 must still be caught up front.
+        let migrations = [migration(1, &[9, 9, 9]), migration(2, &[4, 5, 6])];
+        let migrator = Migrator::new(&migrations);
+        let applied = [AppliedMigration { version: 1, checksum: vec![1, 2, 3] }];
+
+        assert!(migrator.verify_checksums(&applied).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! A connection manager for the `r2d2` generic connection pool.
+//!
+//! This replaces the separate `r2d2-postgres` crate: because the manager
+//! lives here, it can check a checked-in connection's `is_desynchronized`
+//! state directly from `r2d2`'s `has_broken` hook, and repair it with
+//! `resync` instead of always evicting it.
+use std::net::ToSocketAddrs;
+
+use error::{Error, Result};
+use Connection;
+
+/// An `r2d2::ManageConnection` for `Connection`s.
+pub struct PostgresConnectionManager<A> {
+    addr: A,
+}
+
+impl<A> PostgresConnectionManager<A>
+where
+    A: ToSocketAddrs + Clone + Send + Sync + 'static,
+{
+    /// Creates a new `PostgresConnectionManager`.
+    ///
+    /// Connections are opened by passing `addr` to `Connection::connect`.
+    pub fn new(addr: A) -> PostgresConnectionManager<A> {
+        PostgresConnectionManager { addr }
+    }
+}
+
+impl<A> ::r2d2::ManageConnection for PostgresConnectionManager<A>
+where
+    A: ToSocketAddrs + Clone + Send + Sync + 'static,
+{
+    type Connection = Connection;
+    type Error = Error;
+
+    fn connect(&self) -> ::std::result::Result<Connection, Error> {
+        Connection::connect(self.addr.clone())
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> ::std::result::Result<(), Error> {
+        if conn.is_desynchronized() {
+            return Err(Error::IoError(::desynchronized()));
+        }
+        conn.ping()
+    }
+
+    fn has_broken(&self, conn: &mut Connection) -> bool {
+        has_broken(conn)
+    }
+}
+
+/// A connection whose desync state can be inspected and repaired, as
+/// `Connection` and `InnerConnection` already allow.
+///
+/// Exists so `has_broken`'s repair-vs-evict branching can be exercised
+/// against a fake in tests without a live socket.
+trait Desyncable {
+    fn is_desynchronized(&self) -> bool;
+    fn resync(&mut self) -> Result<()>;
+}
+
+impl Desyncable for Connection {
+    fn is_desynchronized(&self) -> bool {
+        Connection::is_desynchronized(self)
+    }
+
+    fn resync(&mut self) -> Result<()> {
+        Connection::resync(self)
+    }
+}
+
+fn has_broken<C: Desyncable>(conn: &mut C) -> bool {
+    // A desynchronized connection isn't necessarily unusable -- try to
+    // repair it with a resync before evicting it from the pool.
+    if conn.is_desynchronized() && conn.resync().is_err() {
+        return true;
+    }
+    conn.is_desynchronized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeConnection {
+        desynchronized: bool,
+        resync_ok: bool,
+    }
+
+    impl Desyncable for FakeConnection {
+        fn is_desynchronized(&self) -> bool {
+            self.desynchronized
+        }
+
+        fn resync(&mut self) -> Result<()> {
+            if self.resync_ok {
+                self.desynchronized = false;
+                Ok(())
+            } else {
+                Err(Error::IoError(::desynchronized()))
+            }
+        }
+    }
+
+    #[test]
+    fn has_broken_keeps_a_synchronized_connection() {
+        let mut conn = FakeConnection { desynchronized: false, resync_ok: false };
+        assert!(!has_broken(&mut conn));
+    }
+
+    #[test]
+    fn has_broken_keeps_a_connection_that_resyncs_successfully() {
+        let mut conn = FakeConnection { desynchronized: true, resync_ok: true };
+        assert!(!has_broken(&mut conn));
+    }
+
+    #[test]
+    fn has_broken_evicts_a_connection_that_fails_to_resync() {
+        let mut conn = FakeConnection { desynchronized: true, resync_ok: false };
+        assert!(has_broken(&mut conn));
+    }
+}